@@ -0,0 +1,131 @@
+//! Locating a Chrome-family browser executable without shelling out to `which`.
+
+#[cfg(target_os = "windows")]
+use std::path::PathBuf;
+
+/// Environment variable that, if set, is used in place of auto-detection.
+const BROWSER_ENV_VAR: &str = "CAPTURE_BROWSER";
+
+const BROWSER_CANDIDATES: &[&str] = &[
+    "chrome",
+    "chromium",
+    "google-chrome",
+    "google-chrome-stable",
+    "chromium-browser",
+    "microsoft-edge",
+    "microsoft-edge-stable",
+    "brave-browser",
+];
+
+/// Find a Chrome-family browser: `CAPTURE_BROWSER` first, then `PATH`, then
+/// (on Windows) the registry and well-known install locations.
+pub fn find_browser() -> Option<String> {
+    if let Ok(browser) = std::env::var(BROWSER_ENV_VAR) {
+        if !browser.is_empty() {
+            return Some(browser);
+        }
+    }
+
+    for candidate in BROWSER_CANDIDATES {
+        if let Ok(path) = which::which(candidate) {
+            return Some(path.display().to_string());
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    if let Some(path) = windows::find_installed() {
+        return Some(path.display().to_string());
+    }
+
+    None
+}
+
+/// The candidates `find_browser` searches, for use in error messages.
+pub fn candidates() -> &'static [&'static str] {
+    BROWSER_CANDIDATES
+}
+
+#[cfg(target_os = "windows")]
+mod windows {
+    use super::PathBuf;
+    use std::path::Path;
+    use winreg::enums::HKEY_LOCAL_MACHINE;
+    use winreg::RegKey;
+
+    const APP_PATHS_EXES: &[&str] = &["chrome.exe", "msedge.exe", "brave.exe"];
+
+    const PROGRAM_FILES_CANDIDATES: &[&str] = &[
+        r"Google\Chrome\Application\chrome.exe",
+        r"Microsoft\Edge\Application\msedge.exe",
+        r"BraveSoftware\Brave-Browser\Application\brave.exe",
+    ];
+
+    /// Read `HKLM\SOFTWARE\Microsoft\Windows\CurrentVersion\App Paths\<exe>`
+    /// for each known browser, falling back to `%ProgramFiles%` locations.
+    pub fn find_installed() -> Option<PathBuf> {
+        if let Some(path) = find_via_app_paths() {
+            return Some(path);
+        }
+        find_via_program_files()
+    }
+
+    fn find_via_app_paths() -> Option<PathBuf> {
+        let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+        for exe in APP_PATHS_EXES {
+            let key_path = format!(r"SOFTWARE\Microsoft\Windows\CurrentVersion\App Paths\{exe}");
+            if let Ok(key) = hklm.open_subkey(&key_path) {
+                if let Ok(path) = key.get_value::<String, _>("") {
+                    if Path::new(&path).is_file() {
+                        return Some(PathBuf::from(path));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn find_via_program_files() -> Option<PathBuf> {
+        for env_var in ["ProgramFiles", "ProgramFiles(x86)"] {
+            let Ok(program_files) = std::env::var(env_var) else {
+                continue;
+            };
+            for suffix in PROGRAM_FILES_CANDIDATES {
+                let path = Path::new(&program_files).join(suffix);
+                if path.is_file() {
+                    return Some(path);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Serializes tests that mutate `CAPTURE_BROWSER`, since env vars are
+    /// process-global and tests run concurrently.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn find_browser_prefers_the_capture_browser_env_var() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        std::env::set_var(BROWSER_ENV_VAR, "/usr/bin/fake-browser");
+        let found = find_browser();
+        std::env::remove_var(BROWSER_ENV_VAR);
+
+        assert_eq!(found, Some("/usr/bin/fake-browser".to_string()));
+    }
+
+    #[test]
+    fn find_browser_ignores_an_empty_capture_browser_env_var() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        std::env::set_var(BROWSER_ENV_VAR, "");
+        let found = find_browser();
+        std::env::remove_var(BROWSER_ENV_VAR);
+
+        assert_ne!(found, Some(String::new()));
+    }
+}
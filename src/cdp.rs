@@ -0,0 +1,336 @@
+//! Native capture over the Chrome DevTools Protocol, used when `--engine cdp`
+//! is passed instead of shelling out to `single-file`, and for every
+//! `--format png`/`pdf` capture.
+
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use serde_json::{json, Value};
+use tempfile::TempDir;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{connect, Message, WebSocket};
+
+const NAVIGATION_TIMEOUT: Duration = Duration::from_secs(30);
+const DEVTOOLS_LISTENING_PREFIX: &str = "DevTools listening on ";
+/// CSS pixels per inch, used to convert `--width`/`--height` into the inches
+/// `Page.printToPDF` wants for paper size.
+const CSS_PIXELS_PER_INCH: f64 = 96.0;
+
+/// Output format for a CDP capture.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Pdf,
+}
+
+/// A running headless Chrome instance plus the DevTools session attached to it.
+///
+/// Kills the child process and removes the temporary profile directory on drop.
+pub struct CdpSession {
+    child: Child,
+    _user_data_dir: TempDir,
+    socket: WebSocket<MaybeTlsStream<std::net::TcpStream>>,
+    next_id: u64,
+    /// Frames read off the socket that didn't match what we were waiting for
+    /// at the time (e.g. an event that arrives before a command's ack), kept
+    /// around so a later `send`/`wait_for_event` can still find them.
+    pending: VecDeque<Value>,
+}
+
+impl CdpSession {
+    /// Launch `browser` headless and connect to its DevTools websocket.
+    pub fn launch(browser: &str) -> Result<Self, String> {
+        let user_data_dir =
+            TempDir::new().map_err(|e| format!("failed to create temp profile dir: {e}"))?;
+
+        let mut child = Command::new(browser)
+            .arg("--headless")
+            .arg("--remote-debugging-port=0")
+            .arg(format!(
+                "--user-data-dir={}",
+                user_data_dir.path().display()
+            ))
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to launch {browser}: {e}"))?;
+
+        let stderr = child.stderr.take().expect("stderr was piped");
+        let ws_url = match read_devtools_url(stderr).recv_timeout(NAVIGATION_TIMEOUT) {
+            Ok(url) => url,
+            Err(_) => {
+                let _ = child.kill();
+                return Err(
+                    "browser exited or timed out before printing a DevTools websocket URL"
+                        .to_string(),
+                );
+            }
+        };
+
+        let (socket, _) = connect(&ws_url).map_err(|e| {
+            let _ = child.kill();
+            format!("failed to connect to {ws_url}: {e}")
+        })?;
+
+        Ok(Self {
+            child,
+            _user_data_dir: user_data_dir,
+            socket,
+            next_id: 1,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Read the next text frame off the socket, skipping non-text control frames.
+    fn recv_one(&mut self) -> Result<Value, String> {
+        loop {
+            let message = self
+                .socket
+                .read()
+                .map_err(|e| format!("lost DevTools connection: {e}"))?;
+            if let Message::Text(text) = message {
+                return serde_json::from_str(&text).map_err(|e| format!("bad CDP frame: {e}"));
+            }
+        }
+    }
+
+    /// Return the first frame (already-queued or freshly read) matching `pred`,
+    /// queueing any frame that doesn't match so a later caller can still find it.
+    fn next_matching(
+        &mut self,
+        deadline: Option<Instant>,
+        pred: impl Fn(&Value) -> bool,
+    ) -> Result<Value, String> {
+        if let Some(pos) = self.pending.iter().position(&pred) {
+            return Ok(self.pending.remove(pos).unwrap());
+        }
+
+        loop {
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err("timed out waiting for a DevTools response".to_string());
+                    }
+                    Some(deadline - now)
+                }
+                None => None,
+            };
+            // Bound the blocking socket read too, so a silent connection
+            // still hits the deadline.
+            if let MaybeTlsStream::Plain(stream) = self.socket.get_ref() {
+                let _ = stream.set_read_timeout(timeout);
+            }
+            let frame = match self.recv_one() {
+                Ok(frame) => frame,
+                Err(_) if deadline.is_some_and(|d| Instant::now() >= d) => {
+                    return Err("timed out waiting for a DevTools response".to_string());
+                }
+                Err(e) => return Err(e),
+            };
+            if pred(&frame) {
+                return Ok(frame);
+            }
+            self.pending.push_back(frame);
+        }
+    }
+
+    /// Send a CDP command and return its `result` payload.
+    fn send(&mut self, method: &str, params: Value) -> Result<Value, String> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let request = json!({ "id": id, "method": method, "params": params });
+        self.socket
+            .send(Message::Text(request.to_string()))
+            .map_err(|e| format!("failed to send {method}: {e}"))?;
+
+        let frame = self
+            .next_matching(None, |frame| {
+                frame.get("id").and_then(Value::as_u64) == Some(id)
+            })
+            .map_err(|e| format!("{method}: {e}"))?;
+
+        if let Some(error) = frame.get("error") {
+            return Err(format!("{method} failed: {error}"));
+        }
+        Ok(frame.get("result").cloned().unwrap_or(Value::Null))
+    }
+
+    /// Block until `event` is observed, with a fixed navigation timeout.
+    fn wait_for_event(&mut self, event: &str) -> Result<Value, String> {
+        let deadline = Instant::now() + NAVIGATION_TIMEOUT;
+        let frame = self
+            .next_matching(Some(deadline), |frame| {
+                frame.get("method").and_then(Value::as_str) == Some(event)
+            })
+            .map_err(|e| format!("waiting for {event}: {e}"))?;
+        Ok(frame.get("params").cloned().unwrap_or(Value::Null))
+    }
+
+    /// Navigate to `url` and wait for the page's load event to fire.
+    pub fn navigate(&mut self, url: &str) -> Result<(), String> {
+        self.send("Page.enable", json!({}))?;
+        self.send("Page.navigate", json!({ "url": url }))?;
+        self.wait_for_event("Page.loadEventFired")?;
+        Ok(())
+    }
+
+    /// Capture the loaded page as a self-contained MHTML document.
+    pub fn capture_mhtml(&mut self) -> Result<String, String> {
+        let result = self.send("Page.captureSnapshot", json!({ "format": "mhtml" }))?;
+        result
+            .get("data")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| "Page.captureSnapshot returned no data".to_string())
+    }
+
+    /// Override the emulated viewport size before a screenshot or PDF capture.
+    /// A `height` of 0 disables the override entirely rather than sizing to
+    /// content, so callers that want the full scrollable page must measure
+    /// it first (see `content_height`) and pass the real height.
+    pub fn set_device_metrics(&mut self, width: u32, height: u32) -> Result<(), String> {
+        self.send(
+            "Emulation.setDeviceMetricsOverride",
+            json!({
+                "width": width,
+                "height": height,
+                "deviceScaleFactor": 1,
+                "mobile": false,
+            }),
+        )?;
+        Ok(())
+    }
+
+    /// Return the loaded page's full scrollable height in CSS pixels, for
+    /// sizing the viewport to the whole page on `--full-page` captures.
+    pub fn content_height(&mut self) -> Result<u32, String> {
+        let result = self.send("Page.getLayoutMetrics", json!({}))?;
+        result
+            .get("cssContentSize")
+            .and_then(|size| size.get("height"))
+            .and_then(Value::as_f64)
+            .map(|height| height.round() as u32)
+            .ok_or_else(|| "Page.getLayoutMetrics returned no cssContentSize.height".to_string())
+    }
+
+    /// Capture the loaded page as a PNG screenshot, decoded to raw bytes.
+    pub fn capture_screenshot(&mut self) -> Result<Vec<u8>, String> {
+        let result = self.send(
+            "Page.captureScreenshot",
+            json!({ "format": "png", "captureBeyondViewport": true }),
+        )?;
+        let data = result
+            .get("data")
+            .and_then(Value::as_str)
+            .ok_or_else(|| "Page.captureScreenshot returned no data".to_string())?;
+        BASE64
+            .decode(data)
+            .map_err(|e| format!("bad base64 screenshot data: {e}"))
+    }
+
+    /// Print the loaded page to PDF, decoded to raw bytes. `viewport`, when
+    /// given, sets the PDF paper size to match `--width`/`--height`.
+    /// `--full-page` has no effect here: `Page.printToPDF` already paginates
+    /// the whole document, and its `preferCSSPageSize` option means something
+    /// unrelated (defer to the page's own `@page` CSS size).
+    pub fn capture_pdf(&mut self, viewport: Option<&Viewport>) -> Result<Vec<u8>, String> {
+        let mut params = json!({});
+        if let Some(viewport) = viewport {
+            params = json!({
+                "paperWidth": viewport.width as f64 / CSS_PIXELS_PER_INCH,
+                "paperHeight": viewport.height as f64 / CSS_PIXELS_PER_INCH,
+            });
+        }
+
+        let result = self.send("Page.printToPDF", params)?;
+        let data = result
+            .get("data")
+            .and_then(Value::as_str)
+            .ok_or_else(|| "Page.printToPDF returned no data".to_string())?;
+        BASE64
+            .decode(data)
+            .map_err(|e| format!("bad base64 PDF data: {e}"))
+    }
+}
+
+impl Drop for CdpSession {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Watch `stderr` for the `DevTools listening on ws://...` line Chrome prints
+/// on startup, forwarding it on the returned channel as soon as it's seen.
+/// The background thread keeps draining `stderr` for the life of the child
+/// so Chrome's later warnings don't write to a closed pipe and trigger
+/// SIGPIPE.
+fn read_devtools_url(stderr: impl std::io::Read + Send + 'static) -> mpsc::Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in std::io::BufReader::new(stderr).lines() {
+            let Ok(line) = line else { break };
+            if let Some(url) = line.strip_prefix(DEVTOOLS_LISTENING_PREFIX) {
+                let _ = tx.send(url.trim().to_string());
+            }
+        }
+    });
+    rx
+}
+
+/// Requested viewport size for a screenshot or PDF capture. `full_page` only
+/// affects PNG screenshots (the height is replaced with the page's real
+/// scrollable height); PDF output already paginates the whole document, so
+/// it has no effect there.
+pub struct Viewport {
+    pub width: u32,
+    pub height: u32,
+    pub full_page: bool,
+}
+
+/// Capture `url` as an MHTML file at `output` using a headless Chrome session.
+pub fn capture(browser: &str, url: &str, output: &str) -> Result<(), String> {
+    let mut session = CdpSession::launch(browser)?;
+    session.navigate(url)?;
+    let mhtml = session.capture_mhtml()?;
+    std::fs::write(output, mhtml).map_err(|e| format!("failed to write {output}: {e}"))?;
+    Ok(())
+}
+
+/// Capture `url` as a PNG screenshot or PDF at `output` using a headless Chrome session.
+pub fn capture_image(
+    browser: &str,
+    url: &str,
+    format: ImageFormat,
+    viewport: Option<Viewport>,
+    output: &str,
+) -> Result<(), String> {
+    let mut session = CdpSession::launch(browser)?;
+
+    if let Some(viewport) = &viewport {
+        session.set_device_metrics(viewport.width, viewport.height)?;
+    }
+
+    session.navigate(url)?;
+
+    if let Some(viewport) = &viewport {
+        if viewport.full_page && format == ImageFormat::Png {
+            let height = session.content_height()?;
+            session.set_device_metrics(viewport.width, height)?;
+        }
+    }
+
+    let bytes = match format {
+        ImageFormat::Png => session.capture_screenshot()?,
+        ImageFormat::Pdf => session.capture_pdf(viewport.as_ref())?,
+    };
+    std::fs::write(output, bytes).map_err(|e| format!("failed to write {output}: {e}"))?;
+    Ok(())
+}
@@ -1,74 +1,424 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufRead, Write};
 use std::process::Command;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+mod browser;
+#[cfg(feature = "cdp")]
+mod cdp;
 
 const FILENAME_TEMPLATE: &str = "{url-hostname} - {date-iso} - {page-title}.{filename-extension}";
 
-const BROWSER_CANDIDATES: &[&str] = &[
-    "chrome",
-    "chromium",
-    "google-chrome",
-    "google-chrome-stable",
-    "chromium-browser",
-];
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Engine {
+    /// Shell out to the external `single-file` Node tool
+    SingleFile,
+    /// Drive headless Chrome directly over the DevTools Protocol
+    #[value(name = "cdp")]
+    Cdp,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+enum Format {
+    /// Self-contained HTML page
+    #[default]
+    Html,
+    /// PNG screenshot
+    Png,
+    /// PDF document
+    Pdf,
+}
 
 #[derive(Parser)]
 #[command(name = "capture")]
 #[command(about = "Capture websites as HTML bookmarks")]
 struct Cli {
-    /// URL to capture
-    url: String,
+    /// URL to capture, or "-" to read a list of URLs from stdin
+    url: Option<String>,
+
+    /// File of URLs to capture, one per line (overrides the positional URL)
+    #[arg(long)]
+    input: Option<String>,
 
-    /// Output filename (uses template if not provided)
+    /// Output filename (if not provided: the single-file template, or
+    /// capture.mhtml/png/pdf for cdp captures; rejected in batch mode)
     #[arg(short, long)]
     output: Option<String>,
 
     /// Browser executable path (auto-detected if not provided)
     #[arg(short, long)]
     browser: Option<String>,
-}
 
-fn find_browser() -> Option<String> {
-    for candidate in BROWSER_CANDIDATES {
-        if Command::new("which")
-            .arg(candidate)
-            .output()
-            .map(|o| o.status.success())
-            .unwrap_or(false)
-        {
-            return Some(candidate.to_string());
-        }
-    }
-    None
-}
+    /// Number of URLs to capture concurrently in batch mode
+    #[arg(short, long, default_value_t = 1)]
+    jobs: usize,
 
-fn main() {
-    let cli = Cli::parse();
+    /// Open the captured file in the OS default application after saving
+    #[arg(long)]
+    open: bool,
 
-    let browser = cli.browser.or_else(find_browser).unwrap_or_else(|| {
-        eprintln!("No browser found. Tried: {}", BROWSER_CANDIDATES.join(", "));
-        eprintln!("Specify one with --browser");
-        std::process::exit(1);
-    });
+    /// Capture engine to use
+    #[cfg(feature = "cdp")]
+    #[arg(long, value_enum, default_value_t = Engine::SingleFile)]
+    engine: Engine,
+
+    /// Output format; png and pdf always use headless Chrome over the DevTools
+    /// Protocol and need the "cdp" feature
+    #[arg(long, value_enum, default_value_t = Format::Html)]
+    format: Format,
+
+    /// Viewport width for png/pdf capture
+    #[arg(long, default_value_t = 1280)]
+    width: u32,
+
+    /// Viewport height for png/pdf capture
+    #[arg(long, default_value_t = 720)]
+    height: u32,
+
+    /// Capture the full scrollable page instead of just the viewport
+    /// (png only; pdf output already paginates the whole document)
+    #[arg(long)]
+    full_page: bool,
+}
 
-    println!("Capturing {}", cli.url);
+/// Read a list of URLs, one per line, trimming whitespace and skipping blank lines.
+fn read_url_list(input: &str) -> io::Result<Vec<String>> {
+    let reader: Box<dyn BufRead> = if input == "-" {
+        Box::new(io::BufReader::new(io::stdin()))
+    } else {
+        Box::new(io::BufReader::new(fs::File::open(input)?))
+    };
 
+    Ok(reader
+        .lines()
+        .collect::<io::Result<Vec<_>>>()?
+        .into_iter()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
+
+/// Capture a single URL with `single-file`, returning the path of the saved
+/// file on success. `single-file`'s stdout/stderr are captured and written
+/// to the terminal once it exits.
+fn capture_one(url: &str, browser: &str, output: Option<&str>) -> Result<String, String> {
     let mut cmd = Command::new("single-file");
-    cmd.arg("--browser-executable-path").arg(&browser);
+    cmd.arg("--browser-executable-path").arg(browser);
 
-    if let Some(output) = &cli.output {
-        cmd.arg(&cli.url).arg(output);
+    if let Some(output) = output {
+        cmd.arg(url).arg(output);
     } else {
         cmd.arg("--filename-template")
             .arg(FILENAME_TEMPLATE)
-            .arg(&cli.url);
+            .arg(url);
+    }
+
+    let out = cmd
+        .output()
+        .map_err(|e| format!("failed to execute single-file: {e}"))?;
+    io::stdout().write_all(&out.stdout).ok();
+    io::stderr().write_all(&out.stderr).ok();
+
+    if !out.status.success() {
+        return Err(format!("single-file failed with status: {}", out.status));
+    }
+
+    match output {
+        Some(output) => Ok(output.to_string()),
+        None => parse_saved_path(&out.stdout).ok_or_else(|| {
+            "could not determine the saved file path from single-file's output".to_string()
+        }),
     }
+}
 
-    let status = cmd.status().expect("Failed to execute single-file");
+/// `single-file` prints the path it wrote to as it finishes; take the last
+/// line that looks like a path to the captured file.
+fn parse_saved_path(stdout: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .map(|line| line.trim().trim_matches('"'))
+        .filter(|line| !line.is_empty())
+        .rfind(|line| std::path::Path::new(line).extension().is_some())
+        .map(|line| line.to_string())
+}
+
+/// Open `path` in the OS default application, mirroring how the `open` crate
+/// dispatches per platform.
+fn open_in_viewer(path: &str) -> Result<(), String> {
+    let status = if cfg!(target_os = "macos") {
+        Command::new("open").arg(path).status()
+    } else if cfg!(target_os = "windows") {
+        Command::new("cmd").args(["/C", "start", "", path]).status()
+    } else {
+        Command::new("xdg-open").arg(path).status()
+    }
+    .map_err(|e| format!("failed to open {path}: {e}"))?;
 
     if status.success() {
-        println!("Done");
+        Ok(())
     } else {
-        eprintln!("single-file failed with status: {}", status);
+        Err(format!("opener exited with status: {status}"))
+    }
+}
+
+/// Capture a single URL, buffering `single-file`'s stdout/stderr instead of
+/// inheriting them, so concurrent jobs don't interleave their output.
+fn capture_one_buffered(url: &str, browser: &str) -> (Result<(), String>, Vec<u8>, Vec<u8>) {
+    let mut cmd = Command::new("single-file");
+    cmd.arg("--browser-executable-path")
+        .arg(browser)
+        .arg("--filename-template")
+        .arg(FILENAME_TEMPLATE)
+        .arg(url);
+
+    let output = match cmd.output() {
+        Ok(output) => output,
+        Err(e) => {
+            return (
+                Err(format!("failed to execute single-file: {e}")),
+                Vec::new(),
+                Vec::new(),
+            )
+        }
+    };
+
+    let result = if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!("single-file failed with status: {}", output.status))
+    };
+
+    (result, output.stdout, output.stderr)
+}
+
+/// Capture a list of URLs, running up to `jobs` `single-file` subprocesses
+/// concurrently. Each job's output is buffered and flushed atomically as soon
+/// as the job completes, so output from concurrent jobs never interleaves.
+fn run_batch(urls: &[String], browser: &str, jobs: usize) -> Vec<String> {
+    let queue = Arc::new(Mutex::new(urls.iter().cloned().collect::<VecDeque<_>>()));
+    let (tx, rx) = mpsc::channel();
+
+    let workers: Vec<_> = (0..jobs.max(1))
+        .map(|_| {
+            let queue = Arc::clone(&queue);
+            let tx = tx.clone();
+            let browser = browser.to_string();
+            thread::spawn(move || loop {
+                let url = match queue.lock().unwrap().pop_front() {
+                    Some(url) => url,
+                    None => break,
+                };
+                let (result, stdout, stderr) = capture_one_buffered(&url, &browser);
+                tx.send((url, result, stdout, stderr)).unwrap();
+            })
+        })
+        .collect();
+    drop(tx);
+
+    let mut failures = Vec::new();
+    for (url, result, stdout, stderr) in rx {
+        let stdout_handle = io::stdout();
+        let mut out = stdout_handle.lock();
+        let _ = out.write_all(&stdout);
+        let _ = out.flush();
+        drop(out);
+
+        let stderr_handle = io::stderr();
+        let mut err = stderr_handle.lock();
+        let _ = err.write_all(&stderr);
+
+        match result {
+            Ok(()) => {
+                let _ = writeln!(err, "Done: {}", url);
+            }
+            Err(e) => {
+                let _ = writeln!(err, "Failed: {} ({e})", url);
+                failures.push(url);
+            }
+        }
+    }
+
+    for worker in workers {
+        let _ = worker.join();
+    }
+
+    failures
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let browser = cli
+        .browser
+        .clone()
+        .or_else(browser::find_browser)
+        .unwrap_or_else(|| {
+            eprintln!(
+                "No browser found. Tried: {}",
+                browser::candidates().join(", ")
+            );
+            eprintln!("Specify one with --browser");
+            std::process::exit(1);
+        });
+
+    let urls = match (&cli.input, &cli.url) {
+        (Some(input), _) => read_url_list(input).unwrap_or_else(|e| {
+            eprintln!("Failed to read {}: {}", input, e);
+            std::process::exit(1);
+        }),
+        (None, Some(url)) if url == "-" => read_url_list("-").unwrap_or_else(|e| {
+            eprintln!("Failed to read stdin: {}", e);
+            std::process::exit(1);
+        }),
+        (None, Some(url)) => vec![url.clone()],
+        (None, None) => {
+            eprintln!("No URL given. Pass a URL, \"-\" to read stdin, or --input <file>.");
+            std::process::exit(1);
+        }
+    };
+
+    if urls.len() == 1 && cli.input.is_none() {
+        println!("Capturing {}", urls[0]);
+
+        #[cfg(feature = "cdp")]
+        let path = match cli.format {
+            Format::Html if cli.engine == Engine::Cdp => {
+                let output = cli
+                    .output
+                    .clone()
+                    .unwrap_or_else(|| "capture.mhtml".to_string());
+                cdp::capture(&browser, &urls[0], &output).unwrap_or_else(|e| {
+                    eprintln!("{e}");
+                    std::process::exit(1);
+                });
+                output
+            }
+            Format::Html => {
+                capture_one(&urls[0], &browser, cli.output.as_deref()).unwrap_or_else(|e| {
+                    eprintln!("{e}");
+                    std::process::exit(1);
+                })
+            }
+            Format::Png | Format::Pdf => {
+                let extension = if cli.format == Format::Png {
+                    "png"
+                } else {
+                    "pdf"
+                };
+                let output = cli
+                    .output
+                    .clone()
+                    .unwrap_or_else(|| format!("capture.{extension}"));
+                let image_format = if cli.format == Format::Png {
+                    cdp::ImageFormat::Png
+                } else {
+                    cdp::ImageFormat::Pdf
+                };
+                let viewport = cdp::Viewport {
+                    width: cli.width,
+                    height: cli.height,
+                    full_page: cli.full_page,
+                };
+                cdp::capture_image(&browser, &urls[0], image_format, Some(viewport), &output)
+                    .unwrap_or_else(|e| {
+                        eprintln!("{e}");
+                        std::process::exit(1);
+                    });
+                output
+            }
+        };
+        #[cfg(not(feature = "cdp"))]
+        let path = match cli.format {
+            Format::Html => {
+                capture_one(&urls[0], &browser, cli.output.as_deref()).unwrap_or_else(|e| {
+                    eprintln!("{e}");
+                    std::process::exit(1);
+                })
+            }
+            Format::Png | Format::Pdf => {
+                eprintln!("--format png/pdf requires capture to be built with the \"cdp\" feature");
+                std::process::exit(1);
+            }
+        };
+
+        println!("Done");
+
+        if cli.open {
+            if let Err(e) = open_in_viewer(&path) {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    #[cfg(feature = "cdp")]
+    let engine_unsupported = cli.engine == Engine::Cdp;
+    #[cfg(not(feature = "cdp"))]
+    let engine_unsupported = false;
+
+    if engine_unsupported || cli.format != Format::Html || cli.open || cli.output.is_some() {
+        eprintln!(
+            "--engine, --format, --open and --output are not supported in batch mode (--input or multiple URLs); \
+             run capture once per URL instead"
+        );
         std::process::exit(1);
     }
+
+    println!("Capturing {} URLs with {} job(s)", urls.len(), cli.jobs);
+    let failures = run_batch(&urls, &browser, cli.jobs);
+
+    println!(
+        "Captured {}/{} URLs",
+        urls.len() - failures.len(),
+        urls.len()
+    );
+    if !failures.is_empty() {
+        eprintln!("Failed URLs:");
+        for url in &failures {
+            eprintln!("  {}", url);
+        }
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_url_list_trims_whitespace_and_skips_blank_lines() {
+        let path =
+            std::env::temp_dir().join(format!("capture-test-urls-{}.txt", std::process::id()));
+        fs::write(&path, "  https://a.example \n\n   \nhttps://b.example\n").unwrap();
+
+        let urls = read_url_list(path.to_str().unwrap()).unwrap();
+
+        fs::remove_file(&path).ok();
+        assert_eq!(urls, vec!["https://a.example", "https://b.example"]);
+    }
+
+    #[test]
+    fn read_url_list_errors_on_missing_file() {
+        assert!(read_url_list("/no/such/file-capture-test").is_err());
+    }
+
+    #[test]
+    fn parse_saved_path_picks_the_last_path_like_line() {
+        let stdout = b"Loading page...\n\"/tmp/old.html\"\n/tmp/final.html\nDone\n";
+        assert_eq!(
+            parse_saved_path(stdout),
+            Some("/tmp/final.html".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_saved_path_returns_none_without_a_path_like_line() {
+        let stdout = b"Loading page\nDone\n";
+        assert_eq!(parse_saved_path(stdout), None);
+    }
 }